@@ -0,0 +1,276 @@
+use byteorder::{ByteOrder, LittleEndian};
+use crypto::sha256;
+use primitives::H256;
+use prost::Message;
+use proto2::chain::{BlockHeader, Transaction};
+use std::collections::HashMap;
+
+use crate::hash::TxId;
+use crate::indexed_block::IndexedBlock;
+use crate::IndexedTransaction;
+
+/// A 6-byte short transaction id, as used by BIP-152 compact blocks.
+pub type ShortTxId = [u8; 6];
+
+/// A transaction the sender chose to ship in full alongside the compact
+/// block, addressed by its position in the block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefilledTransaction {
+    pub index: u32,
+    pub transaction: Transaction,
+}
+
+/// A BIP-152-style compact block: a header plus, for each transaction, either
+/// a 6-byte short id (the receiver is expected to already have it in its
+/// mempool) or the full transaction body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    pub nonce: u64,
+    pub short_ids: Vec<ShortTxId>,
+    pub prefilled_txns: Vec<PrefilledTransaction>,
+}
+
+impl IndexedBlock {
+    /// Builds a compact block for this block, keyed by `nonce`, so peers
+    /// that already share the mempool don't need every transaction sent in
+    /// full. Each index in `prefill` is shipped as a full transaction
+    /// instead of a short id (e.g. because it's unlikely to be in a peer's
+    /// mempool); `short_ids` and `prefilled_txns` are built together so the
+    /// two always add up to exactly `self.transactions().len()`.
+    pub fn to_compact(&self, nonce: u64, prefill: &[usize]) -> CompactBlock {
+        let (key0, key1) = short_id_keys(&self.header().raw, nonce);
+
+        let mut short_ids = Vec::new();
+        let mut prefilled_txns = Vec::new();
+        for (index, txn) in self.transactions().iter().enumerate() {
+            if prefill.contains(&index) {
+                prefilled_txns.push(PrefilledTransaction {
+                    index: index as u32,
+                    transaction: txn.raw.clone(),
+                });
+            } else {
+                short_ids.push(short_txid(key0, key1, &txn.hash));
+            }
+        }
+
+        CompactBlock {
+            header: self.header().raw.clone(),
+            nonce,
+            short_ids,
+            prefilled_txns,
+        }
+    }
+}
+
+impl CompactBlock {
+    /// Reconstructs the full block from `mempool`, matching each short id
+    /// against the short id of every candidate transaction. Transactions
+    /// whose short id cannot be found in `mempool` are reported back by
+    /// their position in the block, so the peer can be asked for just
+    /// those.
+    pub fn reconstruct(&self, mempool: &[IndexedTransaction]) -> Result<IndexedBlock, Vec<usize>> {
+        let (key0, key1) = short_id_keys(&self.header, self.nonce);
+
+        let mut by_short_id: HashMap<ShortTxId, &IndexedTransaction> = HashMap::with_capacity(mempool.len());
+        for txn in mempool {
+            by_short_id.insert(short_txid(key0, key1, &txn.hash), txn);
+        }
+
+        let total = self.short_ids.len() + self.prefilled_txns.len();
+        let mut slots: Vec<Option<Transaction>> = vec![None; total];
+        let mut prefilled_seen = vec![false; total];
+        for prefilled in &self.prefilled_txns {
+            let index = prefilled.index as usize;
+            if index >= total || prefilled_seen[index] {
+                // a peer-controlled message claiming an out-of-range or
+                // duplicated prefilled index can't be trusted at all - ask
+                // for every transaction in the block back.
+                return Err((0..total).collect());
+            }
+            prefilled_seen[index] = true;
+            slots[index] = Some(prefilled.transaction.clone());
+        }
+
+        let mut short_ids = self.short_ids.iter();
+        let mut missing = Vec::new();
+        for (index, slot) in slots.iter_mut().enumerate() {
+            if slot.is_some() {
+                continue;
+            }
+            match short_ids.next() {
+                Some(short_id) => match by_short_id.get(short_id) {
+                    Some(txn) => *slot = Some(txn.raw.clone()),
+                    None => missing.push(index),
+                },
+                // fewer short ids than unfilled slots: the message is
+                // malformed, not just missing from our mempool, but still
+                // report it as missing rather than unwrapping a `None` below.
+                None => missing.push(index),
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+
+        let transactions = slots.into_iter().map(|txn| txn.unwrap()).collect();
+        let block = IndexedBlock::from_header_and_txns(self.header.clone(), transactions);
+
+        // short ids are only a 48-bit SipHash truncation, so a mempool
+        // collision (or a malicious peer) can resolve a short id to the
+        // wrong transaction; never hand back a block whose contents don't
+        // actually match the header's committed merkle root.
+        if !block.verify_merkle_root_hash() {
+            return Err((0..total).collect());
+        }
+
+        Ok(block)
+    }
+}
+
+/// Derives the two SipHash-2-4 keys for a compact block, per BIP-152:
+/// `sha256(header_bytes || nonce_le)`, split into two little-endian u64s.
+fn short_id_keys(header: &BlockHeader, nonce: u64) -> (u64, u64) {
+    let mut buf = Vec::new();
+    header.encode(&mut buf).unwrap();
+    buf.extend_from_slice(&nonce.to_le_bytes());
+    let digest = sha256(&buf);
+
+    let key0 = LittleEndian::read_u64(&digest.as_bytes()[0..8]);
+    let key1 = LittleEndian::read_u64(&digest.as_bytes()[8..16]);
+    (key0, key1)
+}
+
+fn short_txid(key0: u64, key1: u64, txid: &TxId) -> ShortTxId {
+    let hash = siphash24(key0, key1, txid.as_bytes());
+    let mut short_id = [0u8; 6];
+    short_id.copy_from_slice(&hash.to_le_bytes()[..6]);
+    short_id
+}
+
+/// Minimal SipHash-2-4 (2 compression rounds, 4 finalization rounds) over a
+/// byte slice, as specified by BIP-152 for short transaction ids.
+fn siphash24(key0: u64, key1: u64, data: &[u8]) -> u64 {
+    let mut v0 = key0 ^ 0x736f_6d65_7073_6575;
+    let mut v1 = key1 ^ 0x646f_7261_6e64_6f6d;
+    let mut v2 = key0 ^ 0x6c79_6765_6e65_7261;
+    let mut v3 = key1 ^ 0x7465_6462_7974_6573;
+
+    macro_rules! round {
+        () => {
+            v0 = v0.wrapping_add(v1);
+            v1 = v1.rotate_left(13);
+            v1 ^= v0;
+            v0 = v0.rotate_left(32);
+            v2 = v2.wrapping_add(v3);
+            v3 = v3.rotate_left(16);
+            v3 ^= v2;
+            v0 = v0.wrapping_add(v3);
+            v3 = v3.rotate_left(21);
+            v3 ^= v0;
+            v2 = v2.wrapping_add(v1);
+            v1 = v1.rotate_left(17);
+            v1 ^= v2;
+            v2 = v2.rotate_left(32);
+        };
+    }
+
+    let len = data.len();
+    let chunks = data.chunks_exact(8);
+    let remainder = chunks.remainder();
+
+    for chunk in chunks {
+        let m = LittleEndian::read_u64(chunk);
+        v3 ^= m;
+        round!();
+        round!();
+        v0 ^= m;
+    }
+
+    let mut last_block = [0u8; 8];
+    last_block[..remainder.len()].copy_from_slice(remainder);
+    last_block[7] = (len & 0xff) as u8;
+    let m = LittleEndian::read_u64(&last_block);
+    v3 ^= m;
+    round!();
+    round!();
+    v0 ^= m;
+
+    v2 ^= 0xff;
+    round!();
+    round!();
+    round!();
+    round!();
+
+    v0 ^ v1 ^ v2 ^ v3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> BlockHeader {
+        BlockHeader::default()
+    }
+
+    #[test]
+    fn short_txid_is_deterministic() {
+        let (key0, key1) = short_id_keys(&header(), 42);
+        let txid = TxId::from(H256::default());
+        assert_eq!(short_txid(key0, key1, &txid), short_txid(key0, key1, &txid));
+    }
+
+    #[test]
+    fn short_id_keys_differ_by_nonce() {
+        assert_ne!(short_id_keys(&header(), 1), short_id_keys(&header(), 2));
+    }
+
+    #[test]
+    fn reconstruct_rejects_duplicate_prefilled_index_without_panicking() {
+        let compact = CompactBlock {
+            header: header(),
+            nonce: 7,
+            short_ids: Vec::new(),
+            prefilled_txns: vec![
+                PrefilledTransaction {
+                    index: 0,
+                    transaction: Transaction::default(),
+                },
+                PrefilledTransaction {
+                    index: 0,
+                    transaction: Transaction::default(),
+                },
+            ],
+        };
+
+        assert_eq!(compact.reconstruct(&[]).unwrap_err(), vec![0, 1]);
+    }
+
+    #[test]
+    fn reconstruct_rejects_out_of_range_prefilled_index_without_panicking() {
+        let compact = CompactBlock {
+            header: header(),
+            nonce: 7,
+            short_ids: Vec::new(),
+            prefilled_txns: vec![PrefilledTransaction {
+                index: 5,
+                transaction: Transaction::default(),
+            }],
+        };
+
+        assert_eq!(compact.reconstruct(&[]).unwrap_err(), vec![0]);
+    }
+
+    #[test]
+    fn reconstruct_reports_missing_transactions_instead_of_panicking() {
+        let compact = CompactBlock {
+            header: header(),
+            nonce: 7,
+            short_ids: vec![[0u8; 6], [1u8; 6]],
+            prefilled_txns: Vec::new(),
+        };
+
+        assert_eq!(compact.reconstruct(&[]).unwrap_err(), vec![0, 1]);
+    }
+}
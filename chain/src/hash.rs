@@ -0,0 +1,60 @@
+//! Strongly-typed wrappers over the bare `H256` hashes used throughout this
+//! crate, so a block hash can't be passed where a txid or merkle root is
+//! expected by mistake. Wire encoding is unaffected: these are newtypes
+//! over the same 32 bytes, convertible to/from `H256` at the boundary.
+
+use primitives::H256;
+use std::fmt;
+use std::ops::Deref;
+
+macro_rules! hash_newtype {
+    ($name:ident, $doc:expr) => {
+        #[doc = $doc]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+        pub struct $name(H256);
+
+        impl $name {
+            pub fn from_bytes(bytes: &[u8]) -> Self {
+                $name(H256::from_slice(bytes))
+            }
+
+            pub fn as_bytes(&self) -> &[u8] {
+                self.0.as_bytes()
+            }
+
+            pub fn as_h256(&self) -> &H256 {
+                &self.0
+            }
+        }
+
+        impl From<H256> for $name {
+            fn from(hash: H256) -> Self {
+                $name(hash)
+            }
+        }
+
+        impl From<$name> for H256 {
+            fn from(wrapped: $name) -> Self {
+                wrapped.0
+            }
+        }
+
+        impl Deref for $name {
+            type Target = H256;
+
+            fn deref(&self) -> &H256 {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+hash_newtype!(BlockHash, "The hash of a block header, uniquely identifying a block.");
+hash_newtype!(TxId, "The hash of a transaction.");
+hash_newtype!(MerkleRoot, "The root of a block's transaction merkle tree.");
@@ -1,5 +1,4 @@
 use byteorder::{ByteOrder, BE};
-use crypto::sha256;
 use lazy_static::lazy_static;
 use primitives::H256;
 use prost::Message;
@@ -8,7 +7,8 @@ use proto2::common::BlockId;
 use std::cmp;
 use std::collections::HashSet;
 
-use crate::merkle_root::MerkleTree;
+use crate::hash::{BlockHash, MerkleRoot, TxId};
+use crate::merkle_root::{self, MerkleTree, PartialMerkleTree};
 use crate::{IndexedBlockHeader, IndexedTransaction};
 
 lazy_static! {
@@ -38,8 +38,14 @@ lazy_static! {
 
 #[derive(Debug, Clone)]
 pub struct IndexedBlock {
-    pub header: IndexedBlockHeader,
-    pub transactions: Vec<IndexedTransaction>,
+    // private so `number`/`encoded_len`/`merkle_root` below, cached from
+    // these at construction time, can't be silently desynced by a caller
+    // mutating the block after the fact; use `header()`/`transactions()`.
+    header: IndexedBlockHeader,
+    transactions: Vec<IndexedTransaction>,
+    number: i64,
+    encoded_len: usize,
+    merkle_root: H256,
 }
 
 impl cmp::PartialEq for IndexedBlock {
@@ -50,9 +56,20 @@ impl cmp::PartialEq for IndexedBlock {
 
 impl IndexedBlock {
     pub fn new(header: IndexedBlockHeader, transactions: Vec<IndexedTransaction>) -> Self {
+        let number = BE::read_u64(&header.hash.as_bytes()[..8]) as i64;
+        let merkle_root = merkle_root(&transactions);
+        let encoded_len = Block {
+            block_header: Some(header.raw.clone()),
+            transactions: transactions.iter().map(|tx| tx.raw.clone()).collect(),
+        }
+        .encoded_len();
+
         IndexedBlock {
-            header: header,
-            transactions: transactions,
+            header,
+            transactions,
+            number,
+            encoded_len,
+            merkle_root,
         }
     }
 
@@ -85,12 +102,20 @@ impl IndexedBlock {
         Self::new(IndexedBlockHeader::from_raw(block_header), transactions)
     }
 
-    pub fn hash(&self) -> &H256 {
-        &self.header.hash
+    pub fn header(&self) -> &IndexedBlockHeader {
+        &self.header
+    }
+
+    pub fn transactions(&self) -> &[IndexedTransaction] {
+        &self.transactions
+    }
+
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash
     }
 
     pub fn number(&self) -> i64 {
-        BE::read_u64(&self.header.hash.as_bytes()[..8]) as i64
+        self.number
     }
 
     pub fn block_id(&self) -> BlockId {
@@ -108,46 +133,53 @@ impl IndexedBlock {
     }
 
     pub fn size(&self) -> usize {
-        self.clone().into_raw_block().encoded_len()
+        self.encoded_len
     }
 
     pub fn merkle_root_hash(&self) -> &[u8] {
         &self.header.raw.raw_data.as_ref().unwrap().merkle_root_hash
     }
 
+    /// The merkle root computed from `self.transactions`, cached at
+    /// construction time. Compare against `merkle_root_hash()` to check
+    /// whether the block header's claimed root actually matches.
+    pub fn merkle_root(&self) -> MerkleRoot {
+        MerkleRoot::from(self.merkle_root)
+    }
+
+    /// Builds a compact proof that each hash in `txids` is included in this
+    /// block, so a light client can authenticate it against
+    /// `merkle_root_hash()` without downloading every transaction.
+    ///
+    /// Returns `Err(merkle_root::Error::EmptyTree)` if this block has no
+    /// transactions.
+    pub fn build_merkle_proof(&self, txids: &[TxId]) -> Result<PartialMerkleTree, merkle_root::Error> {
+        let leaves = self.transactions.iter().map(|txn| txn.hash).collect::<Vec<_>>();
+        let matches = leaves.iter().map(|txid| txids.contains(txid)).collect::<Vec<_>>();
+        PartialMerkleTree::build(&leaves, &matches)
+    }
+
     pub fn verify_merkle_root_hash(&self) -> bool {
         if BLOCK_WHITELIST.contains(&self.number()) {
             eprintln!(
                 "block {} in whitelist, merkle tree match={}",
                 self.number(),
-                self.merkle_root_hash() == merkle_root(&self.transactions).as_bytes()
+                self.merkle_root_hash() == self.merkle_root.as_bytes()
             );
             return true;
         }
-        if self.merkle_root_hash() == merkle_root(&self.transactions).as_bytes() {
+        if self.merkle_root_hash() == self.merkle_root.as_bytes() {
             true
         } else {
             eprintln!("block saved => {:?}", H256::from_slice(self.merkle_root_hash()));
-            eprintln!("calculated  => {:?}", merkle_root(&self.transactions));
+            eprintln!("calculated  => {:?}", self.merkle_root);
             false
         }
     }
 }
 
 fn merkle_root(transactions: &[IndexedTransaction]) -> H256 {
-    let hashes = transactions
-        .iter()
-        .map(|txn| get_transaction_hash_for_merkle_root(&txn.raw))
-        .collect::<Vec<_>>();
-    // println!("hashes => {:?}", hashes);
+    let hashes = transactions.iter().map(|txn| *txn.hash.as_h256()).collect::<Vec<_>>();
     let tree = MerkleTree::from_vec(hashes);
     *tree.root_hash()
 }
-
-fn get_transaction_hash_for_merkle_root(transaction: &Transaction) -> H256 {
-    let mut buf = Vec::with_capacity(255);
-    // won't fail?
-    transaction.encode(&mut buf).unwrap();
-    // println!("raw => {:?}", buf);
-    sha256(&buf)
-}
@@ -0,0 +1,31 @@
+use crypto::sha256;
+use primitives::H256;
+use prost::Message;
+use proto2::chain::BlockHeader;
+
+use crate::hash::BlockHash;
+
+/// A `BlockHeader` paired with its hash, computed once at construction so
+/// callers never need to re-derive it.
+#[derive(Debug, Clone)]
+pub struct IndexedBlockHeader {
+    pub raw: BlockHeader,
+    pub hash: BlockHash,
+}
+
+impl IndexedBlockHeader {
+    pub fn new(raw: BlockHeader, hash: BlockHash) -> Self {
+        IndexedBlockHeader { raw, hash }
+    }
+
+    pub fn from_raw(raw: BlockHeader) -> Self {
+        let hash = BlockHash::from(hash_header(&raw));
+        Self::new(raw, hash)
+    }
+}
+
+fn hash_header(header: &BlockHeader) -> H256 {
+    let mut buf = Vec::new();
+    header.encode(&mut buf).unwrap();
+    sha256(&buf)
+}
@@ -0,0 +1,33 @@
+use crypto::sha256;
+use primitives::H256;
+use prost::Message;
+use proto2::chain::Transaction;
+
+use crate::hash::TxId;
+
+/// A `Transaction` paired with its hash, computed once at construction so
+/// callers never need to re-derive it.
+#[derive(Debug, Clone)]
+pub struct IndexedTransaction {
+    pub raw: Transaction,
+    pub hash: TxId,
+}
+
+impl IndexedTransaction {
+    pub fn new(raw: Transaction, hash: TxId) -> Self {
+        IndexedTransaction { raw, hash }
+    }
+
+    pub fn from_raw(raw: Transaction) -> Self {
+        let hash = TxId::from(hash_transaction(&raw));
+        Self::new(raw, hash)
+    }
+}
+
+/// Hashes an encoded transaction. This is both the transaction's id and the
+/// leaf hash used when building the block's merkle root.
+pub(crate) fn hash_transaction(transaction: &Transaction) -> H256 {
+    let mut buf = Vec::with_capacity(255);
+    transaction.encode(&mut buf).unwrap();
+    sha256(&buf)
+}
@@ -0,0 +1,369 @@
+use crypto::sha256;
+use primitives::H256;
+use std::fmt;
+
+use crate::hash::{MerkleRoot, TxId};
+
+/// A simple binary merkle tree over leaf hashes.
+///
+/// Internal nodes are `sha256(left || right)`; when a level has an odd
+/// number of nodes, the last one is duplicated to pair with itself. Note
+/// this is a single-SHA256 scheme, unlike Bitcoin's double-SHA256 trees.
+pub struct MerkleTree {
+    nodes: Vec<H256>,
+}
+
+impl MerkleTree {
+    pub fn from_vec(leaves: Vec<H256>) -> Self {
+        let mut nodes = leaves;
+        if nodes.is_empty() {
+            nodes.push(H256::default());
+        }
+        let mut level = nodes.clone();
+        while level.len() > 1 {
+            level = hash_level(&level);
+            nodes.extend(level.iter().cloned());
+        }
+        MerkleTree { nodes }
+    }
+
+    pub fn root_hash(&self) -> &H256 {
+        self.nodes.last().unwrap()
+    }
+}
+
+fn hash_level(level: &[H256]) -> Vec<H256> {
+    level
+        .chunks(2)
+        .map(|pair| {
+            let left = pair[0];
+            let right = pair.get(1).copied().unwrap_or(left);
+            hash_pair(&left, &right)
+        })
+        .collect()
+}
+
+fn hash_pair(left: &H256, right: &H256) -> H256 {
+    let mut buf = Vec::with_capacity(64);
+    buf.extend_from_slice(left.as_bytes());
+    buf.extend_from_slice(right.as_bytes());
+    sha256(&buf)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// `n`, the number of leaves the tree was built over, was zero.
+    EmptyTree,
+    /// `txids` and `matches` passed to `PartialMerkleTree::build` had
+    /// different lengths.
+    LengthMismatch,
+    /// the encoded proof did not consume every bit.
+    UnconsumedBits,
+    /// the encoded proof did not consume every hash.
+    UnconsumedHashes,
+    /// the proof ran out of bits while still descending the tree.
+    NotEnoughBits,
+    /// the proof ran out of hashes while still descending the tree.
+    NotEnoughHashes,
+    /// an internal node duplicated its left child into the right slot while
+    /// claiming to be a parent-of-match, which is the classic merkle tree
+    /// malleability attack (CVE-2012-2459 in Bitcoin Core).
+    MatchedNodeDuplication,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::EmptyTree => write!(f, "partial merkle tree built over zero transactions"),
+            Error::LengthMismatch => write!(f, "partial merkle tree txids and matches length mismatch"),
+            Error::UnconsumedBits => write!(f, "partial merkle tree proof left unused bits"),
+            Error::UnconsumedHashes => write!(f, "partial merkle tree proof left unused hashes"),
+            Error::NotEnoughBits => write!(f, "partial merkle tree proof ran out of bits"),
+            Error::NotEnoughHashes => write!(f, "partial merkle tree proof ran out of hashes"),
+            Error::MatchedNodeDuplication => write!(f, "merkle node duplication inside matched subtree"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A compact proof that a subset of transactions is included in a block,
+/// as used by SPV-style light clients. See BIP-37 for the reference
+/// algorithm this mirrors (adapted to this crate's single-SHA256 tree).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialMerkleTree {
+    /// number of transactions in the original block.
+    n: u32,
+    /// traversal bits, LSB-first, packed into bytes.
+    bits: Vec<u8>,
+    /// hashes left in the proof, consumed in depth-first order.
+    hashes: Vec<H256>,
+}
+
+impl PartialMerkleTree {
+    /// Builds a proof for the leaves in `txids` whose entry at the same
+    /// position in `matches` is `true`.
+    pub fn build(txids: &[TxId], matches: &[bool]) -> Result<Self, Error> {
+        if txids.len() != matches.len() {
+            return Err(Error::LengthMismatch);
+        }
+
+        if txids.is_empty() {
+            return Err(Error::EmptyTree);
+        }
+
+        let n = txids.len() as u32;
+        let height = tree_height(txids.len());
+        let leaves = txids.iter().map(|txid| *txid.as_h256()).collect::<Vec<_>>();
+
+        let mut builder = Builder {
+            txids: &leaves,
+            matches,
+            bits: Vec::new(),
+            hashes: Vec::new(),
+        };
+        builder.traverse_and_build(height, 0);
+
+        Ok(PartialMerkleTree {
+            n,
+            bits: pack_bits(&builder.bits),
+            hashes: builder.hashes,
+        })
+    }
+
+    /// Verifies this proof and returns the reconstructed merkle root plus
+    /// the `(index, txid)` pairs it attests to.
+    pub fn extract_matches(&self) -> Result<(MerkleRoot, Vec<(u32, TxId)>), Error> {
+        if self.n == 0 {
+            return Err(Error::EmptyTree);
+        }
+
+        let height = tree_height(self.n as usize);
+        let bits = unpack_bits(&self.bits);
+
+        let mut extractor = Extractor {
+            n: self.n,
+            bits: &bits,
+            hashes: &self.hashes,
+            bit_pos: 0,
+            hash_pos: 0,
+            matches: Vec::new(),
+        };
+        let root = extractor.traverse_and_extract(height, 0)?;
+
+        // `bits` is unpacked from bytes, so its length is always a multiple
+        // of 8 - compare the number of *bytes* the traversal actually
+        // needed against the number of bytes the proof carries, not the
+        // padded bit count.
+        if (extractor.bit_pos + 7) / 8 != self.bits.len() {
+            return Err(Error::UnconsumedBits);
+        }
+        if extractor.hash_pos != self.hashes.len() {
+            return Err(Error::UnconsumedHashes);
+        }
+
+        let matches = extractor
+            .matches
+            .into_iter()
+            .map(|(index, hash)| (index, TxId::from(hash)))
+            .collect();
+        Ok((MerkleRoot::from(root), matches))
+    }
+}
+
+fn tree_height(n: usize) -> u32 {
+    let mut height = 0;
+    while (1usize << height) < n {
+        height += 1;
+    }
+    height
+}
+
+/// number of nodes in the row `height` levels above the leaves, within a
+/// tree of `n` total leaves.
+fn row_width(n: u32, height: u32) -> u32 {
+    (n + (1 << height) - 1) >> height
+}
+
+fn pack_bits(bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0u8; (bits.len() + 7) / 8];
+    for (i, &bit) in bits.iter().enumerate() {
+        if bit {
+            bytes[i / 8] |= 1 << (i % 8);
+        }
+    }
+    bytes
+}
+
+fn unpack_bits(bytes: &[u8]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(bytes.len() * 8);
+    for &byte in bytes {
+        for i in 0..8 {
+            bits.push(byte & (1 << i) != 0);
+        }
+    }
+    bits
+}
+
+struct Builder<'a> {
+    txids: &'a [H256],
+    matches: &'a [bool],
+    bits: Vec<bool>,
+    hashes: Vec<H256>,
+}
+
+impl<'a> Builder<'a> {
+    fn hash_node(&self, height: u32, pos: u32) -> H256 {
+        if height == 0 {
+            return self.txids[pos as usize];
+        }
+        let width = row_width(self.txids.len() as u32, height - 1);
+        let left = self.hash_node(height - 1, pos * 2);
+        let right = if pos * 2 + 1 < width {
+            self.hash_node(height - 1, pos * 2 + 1)
+        } else {
+            left
+        };
+        hash_pair(&left, &right)
+    }
+
+    fn is_parent_of_match(&self, height: u32, pos: u32) -> bool {
+        let width = row_width(self.txids.len() as u32, height);
+        let from = (pos << height) as usize;
+        let to = cmp_min(((pos + 1) << height) as usize, self.txids.len());
+        debug_assert!(width > 0);
+        self.matches[from..to].iter().any(|&m| m)
+    }
+
+    fn traverse_and_build(&mut self, height: u32, pos: u32) {
+        let parent_of_match = self.is_parent_of_match(height, pos);
+        self.bits.push(parent_of_match);
+
+        if height == 0 || !parent_of_match {
+            self.hashes.push(self.hash_node(height, pos));
+            return;
+        }
+
+        let width = row_width(self.txids.len() as u32, height - 1);
+        self.traverse_and_build(height - 1, pos * 2);
+        if pos * 2 + 1 < width {
+            self.traverse_and_build(height - 1, pos * 2 + 1);
+        }
+    }
+}
+
+fn cmp_min(a: usize, b: usize) -> usize {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+struct Extractor<'a> {
+    n: u32,
+    bits: &'a [bool],
+    hashes: &'a [H256],
+    bit_pos: usize,
+    hash_pos: usize,
+    matches: Vec<(u32, H256)>,
+}
+
+impl<'a> Extractor<'a> {
+    fn traverse_and_extract(&mut self, height: u32, pos: u32) -> Result<H256, Error> {
+        if self.bit_pos >= self.bits.len() {
+            return Err(Error::NotEnoughBits);
+        }
+        let parent_of_match = self.bits[self.bit_pos];
+        self.bit_pos += 1;
+
+        if height == 0 || !parent_of_match {
+            let hash = *self.hashes.get(self.hash_pos).ok_or(Error::NotEnoughHashes)?;
+            self.hash_pos += 1;
+            if height == 0 && parent_of_match {
+                self.matches.push((pos, hash));
+            }
+            return Ok(hash);
+        }
+
+        let width = row_width(self.n, height - 1);
+        let left = self.traverse_and_extract(height - 1, pos * 2)?;
+        let right = if pos * 2 + 1 < width {
+            let right = self.traverse_and_extract(height - 1, pos * 2 + 1)?;
+            if right == left {
+                return Err(Error::MatchedNodeDuplication);
+            }
+            right
+        } else {
+            left
+        };
+        Ok(hash_pair(&left, &right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txid(seed: u8) -> TxId {
+        TxId::from(sha256(&[seed]))
+    }
+
+    #[test]
+    fn round_trips_a_single_fully_matched_transaction() {
+        let txids = vec![txid(0)];
+        let proof = PartialMerkleTree::build(&txids, &[true]).unwrap();
+
+        let (root, matches) = proof.extract_matches().unwrap();
+
+        let tree = MerkleTree::from_vec(vec![*txids[0].as_h256()]);
+        assert_eq!(root.as_h256(), tree.root_hash());
+        assert_eq!(matches, vec![(0, txids[0])]);
+    }
+
+    #[test]
+    fn round_trips_a_subset_match_over_several_transactions() {
+        let txids = (0..7).map(txid).collect::<Vec<_>>();
+        let matches = vec![false, true, false, false, true, false, false];
+        let proof = PartialMerkleTree::build(&txids, &matches).unwrap();
+
+        let (root, matched) = proof.extract_matches().unwrap();
+
+        let tree = MerkleTree::from_vec(txids.iter().map(|t| *t.as_h256()).collect());
+        assert_eq!(root.as_h256(), tree.root_hash());
+        assert_eq!(matched, vec![(1, txids[1]), (4, txids[4])]);
+    }
+
+    #[test]
+    fn round_trips_with_no_matches() {
+        let txids = (0..4).map(txid).collect::<Vec<_>>();
+        let proof = PartialMerkleTree::build(&txids, &[false; 4]).unwrap();
+
+        let (root, matched) = proof.extract_matches().unwrap();
+
+        let tree = MerkleTree::from_vec(txids.iter().map(|t| *t.as_h256()).collect());
+        assert_eq!(root.as_h256(), tree.root_hash());
+        assert!(matched.is_empty());
+    }
+
+    #[test]
+    fn build_rejects_an_empty_block() {
+        assert_eq!(PartialMerkleTree::build(&[], &[]), Err(Error::EmptyTree));
+    }
+
+    #[test]
+    fn build_rejects_mismatched_lengths_instead_of_panicking() {
+        let txids = vec![txid(0), txid(1)];
+        assert_eq!(PartialMerkleTree::build(&txids, &[true]), Err(Error::LengthMismatch));
+    }
+
+    #[test]
+    fn extract_rejects_an_empty_block() {
+        let empty = PartialMerkleTree {
+            n: 0,
+            bits: Vec::new(),
+            hashes: Vec::new(),
+        };
+        assert_eq!(empty.extract_matches().unwrap_err(), Error::EmptyTree);
+    }
+}
@@ -0,0 +1,43 @@
+//! Messages for BIP-152-style compact block relay.
+//!
+//! Full-block propagation wastes bandwidth once a peer already shares most
+//! of our mempool, so peers that opt in via `SendCompact` are handed
+//! `CompactBlockMessage`s instead of full blocks, and fall back to
+//! `GetBlockTxn`/`BlockTxn` for whatever `CompactBlock::reconstruct` could
+//! not fill in from its own mempool.
+
+use chain::compact_block::{CompactBlock, PrefilledTransaction};
+use chain::hash::BlockHash;
+
+/// Sent once per connection to advertise support for, and opt into,
+/// high-bandwidth compact block relay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendCompact {
+    /// Selects high-bandwidth mode, where new blocks are pushed as a
+    /// `CompactBlockMessage` right away instead of waiting for an inv
+    /// round-trip.
+    pub high_bandwidth: bool,
+    pub version: u64,
+}
+
+/// A newly produced block, announced in compact form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactBlockMessage {
+    pub compact_block: CompactBlock,
+}
+
+/// Requests the full transactions at the given block-relative indexes,
+/// sent when reconstructing a `CompactBlockMessage` from the local mempool
+/// left some transactions unresolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetBlockTxn {
+    pub block_hash: BlockHash,
+    pub indexes: Vec<usize>,
+}
+
+/// Answers a `GetBlockTxn` with the requested transactions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockTxn {
+    pub block_hash: BlockHash,
+    pub transactions: Vec<PrefilledTransaction>,
+}
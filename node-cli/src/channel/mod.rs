@@ -0,0 +1,5 @@
+//! Peer-to-peer channel protocol messages.
+
+mod compact;
+
+pub use self::compact::{BlockTxn, CompactBlockMessage, GetBlockTxn, SendCompact};